@@ -1,8 +1,11 @@
 //! Logging utilities.
 
+use crate::theme::{BaseColor, Color, ColorStyle};
+use crate::utils::markup::StyledString;
 use lazy_static::lazy_static;
 use std::cmp::Ord;
 use std::collections::VecDeque;
+use std::io::Write;
 use std::str::FromStr;
 use std::sync::{Mutex,RwLock};
 
@@ -40,35 +43,106 @@ use std::sync::{Mutex,RwLock};
 
 pub struct CursiveLogger;
 
+/// A single `target=level` filter directive, as found in `RUST_LOG`-style strings.
+///
+/// A `name` of `None` means the directive applies to every target, and acts as the
+/// default level when no more specific directive matches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Directive {
+    name: Option<String>,
+    level: log::LevelFilter,
+}
+
+impl Directive {
+    /// Name used for directives synthesized from `set_int_filter_level`.
+    ///
+    /// Keeps the trailing `::` so prefix matching only classifies `cursive_core`'s own
+    /// modules as internal, not unrelated crates that merely share the name prefix
+    /// (e.g. `cursive_core_extra`).
+    const INT_NAME: &'static str = "cursive_core::";
+}
+
+/// Parses a `RUST_LOG`/`CURSIVE_LOG`-style directive string into a list of directives.
+///
+/// Each comma-separated entry is either `target=level` or a bare `level`, which becomes
+/// the default (`name: None`) directive. Entries that fail to parse are skipped.
+fn parse_directives(spec: &str) -> Vec<Directive> {
+    spec.split(',')
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| match entry.split_once('=') {
+            Some((name, level)) => log::LevelFilter::from_str(level)
+                .ok()
+                .map(|level| Directive {
+                    name: Some(name.to_string()),
+                    level,
+                }),
+            None => log::LevelFilter::from_str(entry).ok().map(|level| Directive {
+                name: None,
+                level,
+            }),
+        })
+        .collect()
+}
+
 lazy_static! {
-    // Log filter level for log messages from within cursive
-    static ref INT_FILTER_LEVEL: RwLock<log::LevelFilter> = RwLock::new(log::LevelFilter::Trace);
-    // Log filter level for log messages from sources outside of cursive
-    static ref EXT_FILTER_LEVEL: RwLock<log::LevelFilter> = RwLock::new(log::LevelFilter::Trace);
+    // Directives used to filter log records by target, most specific name wins.
+    static ref DIRECTIVES: RwLock<Vec<Directive>> = RwLock::new(vec![
+        Directive {
+            name: Some(Directive::INT_NAME.to_string()),
+            level: log::LevelFilter::Trace,
+        },
+        Directive {
+            name: None,
+            level: log::LevelFilter::Trace,
+        },
+    ]);
     // Size of log queue
     static ref LOG_SIZE: RwLock<usize> = RwLock::new(1_000);
+    // Runtime-adjustable bound on the number of logs kept in `LOGS`.
+    //
+    // `VecDeque::capacity()` is an allocator-driven over-approximation of what was
+    // `reserve()`d, so it can't be used to enforce `LOG_SIZE` on its own.
+    static ref MAX_LOGS: RwLock<usize> = RwLock::new(1_000);
 }
 
-/// Sets the internal log filter level.
+/// Replaces the directive with the given `name` (or the default directive, for `None`),
+/// inserting it if it wasn't already present.
+fn set_directive(name: Option<String>, level: log::LevelFilter) {
+    let mut directives = DIRECTIVES.write().unwrap();
+    match directives.iter_mut().find(|directive| directive.name == name) {
+        Some(directive) => directive.level = level,
+        None => directives.push(Directive { name, level }),
+    }
+}
+
+/// Sets the internal log filter level, for messages logged from within cursive.
 pub fn set_int_filter_level(level: log::LevelFilter) {
-    *INT_FILTER_LEVEL.write().unwrap() = level;
+    set_directive(Some(Directive::INT_NAME.to_string()), level);
 }
 
-/// Sets the external log filter level.
+/// Sets the external log filter level, for messages logged from outside cursive.
 pub fn set_ext_filter_level(level: log::LevelFilter) {
-    *EXT_FILTER_LEVEL.write().unwrap() = level;
+    set_directive(None, level);
 }
 
-/// Sets log filter levels based on environment variables `RUST_LOG` and `CURSIVE_LOG`.
-/// If `RUST_LOG` is set, then both internal and external log levels are set to match.
-/// If `CURSIVE_LOG` is set, then the internal log level is set to match with precedence over
-/// `RUST_LOG`.
+/// Sets the log filter directives, following `env_logger`'s syntax.
+///
+/// The spec is a comma-separated list of entries, each either `target=level` (filtering
+/// only targets starting with `target`) or a bare `level` (the default for any target
+/// without a more specific match). For example: `my_app::net=trace,cursive_core=warn,info`.
+pub fn set_filter_directives(spec: &str) {
+    *DIRECTIVES.write().unwrap() = parse_directives(spec);
+}
+
+/// Sets log filter directives based on environment variables `RUST_LOG` and `CURSIVE_LOG`.
+/// If `RUST_LOG` is set, it's parsed as a full `env_logger`-style directive spec (see
+/// [`set_filter_directives`]), replacing all current directives.
+/// If `CURSIVE_LOG` is set, it overrides just the internal (`cursive_core::`) level, with
+/// precedence over `RUST_LOG`'s default and `cursive_core` entries; `RUST_LOG` entries
+/// for more specific `cursive_core::` submodules still win for those targets.
 pub fn set_filter_levels_with_env() {
     if let Ok(rust_log) = std::env::var("RUST_LOG") {
-        if let Ok(filter_level) = log::LevelFilter::from_str(&rust_log) {
-            set_int_filter_level(filter_level);
-            set_ext_filter_level(filter_level);
-        }
+        set_filter_directives(&rust_log);
     }
     if let Ok(cursive_log) = std::env::var("CURSIVE_LOG") {
         if let Ok(filter_level) = log::LevelFilter::from_str(&cursive_log) {
@@ -77,14 +151,35 @@ pub fn set_filter_levels_with_env() {
     }
 }
 
-/// Sets the size of the log queue prior to initialization.
-/// Has no effect after calling `init()` or `get_logger()`.
-/// Use `reserve_logs()` instead to increase log size during use.
+/// Sets the size of the log queue.
+///
+/// This updates the live bound enforced on every push, so it takes effect immediately
+/// even after `init()` or `get_logger()` — but unlike `set_log_size_live()`, it does not
+/// trim the existing buffer right away if `log_size` is smaller than the current number
+/// of logs; the excess is dropped on the next push.
 pub fn set_log_size(log_size: usize) {
     *LOG_SIZE.write().unwrap() = log_size;
+    *MAX_LOGS.write().unwrap() = log_size;
+}
+
+/// Resizes the log queue while the application is running, trimming the existing
+/// buffer immediately if it is shrunk.
+pub fn set_log_size_live(log_size: usize) {
+    *LOG_SIZE.write().unwrap() = log_size;
+    *MAX_LOGS.write().unwrap() = log_size;
+    let mut logs = LOGS.lock().unwrap();
+    while logs.len() > log_size {
+        logs.pop_front();
+    }
+}
+
+/// Empties the log queue, for example to give a `DebugView` a clean slate.
+pub fn clear_logs() {
+    LOGS.lock().unwrap().clear();
 }
 
 /// A log record.
+#[derive(Clone)]
 pub struct Record {
     /// Log level used for this record
     pub level: log::Level,
@@ -92,6 +187,103 @@ pub struct Record {
     pub time: time::OffsetDateTime,
     /// Message content
     pub message: String,
+    /// Target of this record, usually the module path the `log` macro was called from.
+    pub target: String,
+    /// Module path the `log` macro was called from, if available.
+    pub module_path: Option<String>,
+    /// Source file the `log` macro was called from, if available.
+    pub file: Option<String>,
+    /// Line number within `file` the `log` macro was called from, if available.
+    pub line: Option<u32>,
+    /// Structured key/value fields attached to this record, in the order they were visited.
+    pub fields: Vec<(String, String)>,
+}
+
+/// Collects a `log::Record`'s structured key/value pairs into a plain `Vec`.
+struct FieldVisitor(Vec<(String, String)>);
+
+impl<'kvs> log::kv::Visitor<'kvs> for FieldVisitor {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+/// Visits `record`'s key/value source and returns the collected fields.
+fn collect_fields(record: &log::Record) -> Vec<(String, String)> {
+    let mut visitor = FieldVisitor(Vec::new());
+    // A `Visitor` can only fail if the source itself errors out; there's nothing
+    // actionable to do here, so any partial results are kept as-is.
+    let _ = record.key_values().visit(&mut visitor);
+    visitor.0
+}
+
+/// Formats a [`Record`] into styled text for display.
+///
+/// Implement this trait to customize how logs are rendered, then register it with
+/// [`set_formatter`].
+pub trait LogFormatter: Send + Sync {
+    /// Formats `record` into a styled line.
+    fn format(&self, record: &Record) -> StyledString;
+}
+
+/// Color used to highlight a given log level in the default formatter.
+fn level_color(level: log::Level) -> Color {
+    match level {
+        log::Level::Error => Color::Dark(BaseColor::Red),
+        log::Level::Warn => Color::Dark(BaseColor::Yellow),
+        log::Level::Info => Color::Dark(BaseColor::Green),
+        log::Level::Debug => Color::Dark(BaseColor::Cyan),
+        log::Level::Trace => Color::Dark(BaseColor::Blue),
+    }
+}
+
+/// Default [`LogFormatter`], producing `<level> [<time>] <target> - <message>` followed by
+/// any fields as ` key=value`, with the level colored by a fixed base color per level
+/// (see [`level_color`]).
+struct DefaultFormatter;
+
+impl LogFormatter for DefaultFormatter {
+    fn format(&self, record: &Record) -> StyledString {
+        let mut line = StyledString::new();
+        line.append_styled(
+            format!("{:<5}", record.level),
+            ColorStyle::front(level_color(record.level)),
+        );
+        line.append_plain(format!(
+            " [{}] {} - {}",
+            record.time, record.target, record.message
+        ));
+        for (key, value) in &record.fields {
+            line.append_plain(format!(" {}={}", key, value));
+        }
+        line
+    }
+}
+
+lazy_static! {
+    static ref FORMATTER: RwLock<Box<dyn LogFormatter>> = RwLock::new(Box::new(DefaultFormatter));
+}
+
+/// Sets the formatter used by [`format_record`] to render log records.
+///
+/// [`DebugView`](crate::views::DebugView) draws `record.message` directly, and the
+/// built-in sinks ([`stderr_sink`], [`file_sink`]) write plain text, so none of them use
+/// this formatter; it only applies where [`format_record`] is called, e.g. from a custom
+/// sink.
+pub fn set_formatter<F: LogFormatter + 'static>(formatter: F) {
+    *FORMATTER.write().unwrap() = Box::new(formatter);
+}
+
+/// Formats `record` using the currently registered [`LogFormatter`].
+///
+/// `DebugView` does not call this; see [`set_formatter`].
+pub fn format_record(record: &Record) -> StyledString {
+    FORMATTER.read().unwrap().format(record)
 }
 
 lazy_static! {
@@ -102,27 +294,158 @@ lazy_static! {
         Mutex::new(VecDeque::new());
 }
 
+/// A sink invoked with every log record, for example to persist it somewhere durable.
+pub type Sink = std::sync::Arc<dyn Fn(&Record) + Send + Sync>;
+
+lazy_static! {
+    static ref SINKS: Mutex<Vec<Sink>> = Mutex::new(Vec::new());
+}
+
+/// Registers a sink invoked with every log record, right after it's pushed onto
+/// cursive's in-memory log queue.
+///
+/// A running TUI app owns the terminal and can't print directly to it, so this is the
+/// way to persist logs for post-mortem debugging. See [`file_sink`] and [`stderr_sink`]
+/// for built-in sinks, or pass any closure of your own.
+pub fn add_sink(sink: Sink) {
+    SINKS.lock().unwrap().push(sink);
+}
+
+fn run_sinks(record: &Record) {
+    // Snapshot the sinks and drop `SINKS` before invoking any of them: a sink (or
+    // anything it calls) may itself log, which would otherwise re-enter `run_sinks`
+    // and deadlock on this non-reentrant mutex.
+    let sinks = SINKS.lock().unwrap().clone();
+    for sink in &sinks {
+        sink(record);
+    }
+}
+
+/// Renders `record` the same way [`DefaultFormatter`] does, but as a plain `String`,
+/// for sinks that have no use for styled text (files, stderr).
+fn plain_line(record: &Record) -> String {
+    let mut line = format!(
+        "{:<5} [{}] {} - {}",
+        record.level, record.time, record.target, record.message
+    );
+    for (key, value) in &record.fields {
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    line
+}
+
+/// Builds a sink that writes each record to stderr, one line per record.
+pub fn stderr_sink() -> Sink {
+    std::sync::Arc::new(|record: &Record| {
+        eprintln!("{}", plain_line(record));
+    })
+}
+
+/// Appends lines to `path`, rotating it to a `.1` sibling (overwriting any earlier
+/// rotation) once it grows past `max_bytes`.
+struct RotatingFile {
+    path: std::path::PathBuf,
+    max_bytes: u64,
+    file: std::fs::File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: std::path::PathBuf, max_bytes: u64) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_bytes,
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(&self) -> std::path::PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(".1");
+        std::path::PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) {
+        let _ = std::fs::rename(&self.path, self.rotated_path());
+        match std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+        {
+            Ok(file) => {
+                self.file = file;
+                self.size = 0;
+            }
+            Err(_) => {}
+        }
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.size >= self.max_bytes {
+            self.rotate();
+        }
+        if writeln!(self.file, "{}", line).is_ok() {
+            self.size += line.len() as u64 + 1;
+        }
+    }
+}
+
+/// Builds a sink that appends each record to the file at `path`, rotating it once it
+/// grows past `max_bytes`. Returns `None` if `path` cannot be opened for appending.
+pub fn file_sink<P: Into<std::path::PathBuf>>(path: P, max_bytes: u64) -> Option<Sink> {
+    let rotating = Mutex::new(RotatingFile::open(path.into(), max_bytes).ok()?);
+    Some(std::sync::Arc::new(move |record: &Record| {
+        rotating.lock().unwrap().write_line(&plain_line(record));
+    }))
+}
+
 /// Log a record in cursive's log queue.
 pub fn log(record: &log::Record) {
-    let mut logs = LOGS.lock().unwrap();
-    // TODO: customize the format? Use colors? Save more info?
-    if logs.len() == logs.capacity() {
-        logs.pop_front();
-    }
-    logs.push_back(Record {
+    let record = Record {
         level: record.level(),
         message: format!("{}", record.args()),
         time: time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc()),
-    });
+        target: record.target().to_string(),
+        module_path: record.module_path().map(str::to_string),
+        file: record.file().map(str::to_string),
+        line: record.line(),
+        fields: collect_fields(record),
+    };
+
+    let max_logs = *MAX_LOGS.read().unwrap();
+    let mut logs = LOGS.lock().unwrap();
+    logs.push_back(record.clone());
+    while logs.len() > max_logs {
+        logs.pop_front();
+    }
+    drop(logs);
+
+    // Sinks run without holding `LOGS`: a sink (or anything it calls) may itself log,
+    // which would otherwise deadlock on this non-reentrant mutex.
+    run_sinks(&record);
 }
 
 impl log::Log for CursiveLogger {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        if metadata.target().starts_with("cursive_core::") {
-            metadata.level() <= *INT_FILTER_LEVEL.read().unwrap()
-        } else {
-            metadata.level() <= *EXT_FILTER_LEVEL.read().unwrap()
-        }
+        let directives = DIRECTIVES.read().unwrap();
+        let target = metadata.target();
+
+        let matched = directives
+            .iter()
+            .filter(|directive| match &directive.name {
+                Some(name) => target.starts_with(name.as_str()),
+                None => true,
+            })
+            .max_by_key(|directive| directive.name.as_ref().map_or(0, String::len));
+
+        let level = matched.map_or(log::LevelFilter::Off, |directive| directive.level);
+        metadata.level() <= level
     }
 
     fn log(&self, record: &log::Record) {
@@ -142,7 +465,14 @@ impl log::Log for CursiveLogger {
 /// [`Cursive::toggle_debug_console()`](crate::Cursive::toggle_debug_console()).
 pub fn init() {
     reserve_logs(*LOG_SIZE.read().unwrap());
-    log::set_max_level((*INT_FILTER_LEVEL.read().unwrap()).max(*EXT_FILTER_LEVEL.read().unwrap()));
+    let max_level = DIRECTIVES
+        .read()
+        .unwrap()
+        .iter()
+        .map(|directive| directive.level)
+        .max()
+        .unwrap_or(log::LevelFilter::Off);
+    log::set_max_level(max_level);
     // This will panic if `set_logger` was already called.
     log::set_logger(&CursiveLogger).unwrap();
 }
@@ -165,3 +495,46 @@ pub fn get_logger() -> CursiveLogger {
 pub fn reserve_logs(n: usize) {
     LOGS.lock().unwrap().reserve(n);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_record(message: &str) {
+        log(&log::Record::builder()
+            .level(log::Level::Info)
+            .target("cursive_core::logger::tests")
+            .args(format_args!("{}", message))
+            .build());
+    }
+
+    // All of this runs in one test, rather than several, since `LOGS`/`MAX_LOGS` are
+    // global state shared across the whole test binary.
+    #[test]
+    fn ring_buffer_respects_its_configured_size() {
+        set_log_size_live(50);
+        clear_logs();
+
+        for i in 0..5_000 {
+            push_record(&format!("message {}", i));
+            assert!(LOGS.lock().unwrap().len() <= 50);
+        }
+        assert_eq!(LOGS.lock().unwrap().len(), 50);
+
+        // Shrinking live trims the existing buffer immediately.
+        set_log_size_live(10);
+        assert_eq!(LOGS.lock().unwrap().len(), 10);
+
+        // Growing live raises the bound without adding anything back.
+        set_log_size_live(20);
+        assert_eq!(LOGS.lock().unwrap().len(), 10);
+        for i in 0..20 {
+            push_record(&format!("more {}", i));
+            assert!(LOGS.lock().unwrap().len() <= 20);
+        }
+        assert_eq!(LOGS.lock().unwrap().len(), 20);
+
+        clear_logs();
+        assert!(LOGS.lock().unwrap().is_empty());
+    }
+}